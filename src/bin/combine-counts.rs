@@ -16,6 +16,7 @@ use std::{
     collections::{
         HashMap,
     },
+    thread,
 };
 
 struct Combiner {
@@ -36,12 +37,56 @@ impl Combiner {
         }
     }
 
-    fn write_to<W: Write>(self, mut out: csv::Writer<W>) -> Result<()> {
+    /// Folds the entries of `other` into `self`, summing counts on collision.
+    ///
+    /// This makes `Combiner` commutative and associative under merging, so
+    /// per-file tallies can be computed independently and combined in any
+    /// order.
+    fn merge(&mut self, other: Combiner) {
+        for (name, count) in other.map {
+            match self.map.get_mut(&name) {
+                Some(old_count) => { *old_count += count; },
+                None => { self.map.insert(name, count); },
+            }
+        }
+    }
+
+    /// Returns the cardinality (number of distinct names) and the mode
+    /// (most frequent name and its count, if any entries are present).
+    fn stats(&self) -> (usize, Option<(&str, u64)>) {
+        let cardinality = self.map.len();
+        let mode = self.map.iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(name, count)| (name.as_str(), *count));
+        (cardinality, mode)
+    }
+
+    /// Writes the combined `(name, count)` tally out as csv.
+    ///
+    /// Entries are sorted by decreasing count, except that `ascending`
+    /// reverses the order. When `limit` is nonzero, only the `limit` most
+    /// frequent names are written. When `stats` is set, the cardinality
+    /// (number of distinct names) and the mode (most frequent name and
+    /// its count) are reported to stderr first.
+    fn write_to<W: Write>(self, mut out: csv::Writer<W>, limit: usize, ascending: bool, stats: bool) -> Result<()> {
+        if stats {
+            match self.stats() {
+                (cardinality, Some((name, count))) => eprintln!("distinct names: {}\nmode: {} ({})", cardinality, name, count),
+                (cardinality, None) => eprintln!("distinct names: {}", cardinality),
+            }
+        }
+
         let mut pairs: Vec<_> = self.map.into_iter().collect();
         pairs.sort_unstable_by(
             |(_, countref1), (_, countref2)|
             countref2.cmp(countref1)
         );
+        if limit > 0 && limit < pairs.len() {
+            pairs.truncate(limit);
+        }
+        if ascending {
+            pairs.reverse();
+        }
         for (name, count) in pairs {
             out.write_record(&[name, count.to_string()])?;
         }
@@ -49,6 +94,26 @@ impl Combiner {
     }
 }
 
+/// Tallies a single input file into its own `Combiner`, so that many
+/// files can be processed concurrently and merged afterward.
+fn combine_file(infname: &str, namecol: usize, countcol: usize, hdrs: bool) -> Result<Combiner> {
+    let mut rdr = ReaderBuilder::new().has_headers(hdrs).from_path(infname)?;
+    let mut line = StringRecord::new();
+    let mut names = Combiner::new();
+    while rdr.read_record(&mut line)? {
+        let name = line.get(namecol).expect(
+            &format!("Missing name on line {} of file {}",
+                    line.position().map(csv::Position::line).unwrap(), infname));
+        let count = str::parse(line.get(countcol)
+                .expect(&format!("Missing count on line {} of file {}",
+                    line.position().map(csv::Position::line).unwrap(), infname))
+            ).expect(&format!("Invalid count on line {} of file {}",
+                line.position().map(csv::Position::line).unwrap(), infname));
+        names.add(name, count);
+    }
+    Ok(names)
+}
+
 fn main() -> Result<()> {
     let args = App::new("combine-counts")
         .about("Sums up tallies form multiple csv files and writes to standard out.")
@@ -74,6 +139,17 @@ fn main() -> Result<()> {
              .short("r")
              .long("header-row")
              .help("Indicates whether the input files have a header row (default no)"))
+        .arg(Arg::with_name("limit")
+             .long("limit")
+             .value_name("N")
+             .help("Only output the N most frequent names (default 0 = no limit)")
+             .takes_value(true))
+        .arg(Arg::with_name("asc")
+             .long("asc")
+             .help("Sort output ascending by count instead of descending"))
+        .arg(Arg::with_name("stats")
+             .long("stats")
+             .help("Report cardinality and mode to stderr after combining"))
         .arg(Arg::with_name("INPUT")
              .help("Input file(s) in csv format")
              .multiple(true)
@@ -96,28 +172,123 @@ fn main() -> Result<()> {
 
     let hdrs = args.is_present("headers");
 
+    let limit = args.value_of("limit")
+        .map(|s| str::parse(s).expect("limit must be a non-negative integer"))
+        .unwrap_or(0usize);
+
+    let ascending = args.is_present("asc");
+    let stats = args.is_present("stats");
+
     assert!(namecol != countcol);
 
-    let mut names = Combiner::new();
+    let infnames: Vec<&str> = args.values_of("INPUT").unwrap().collect();
 
-    for infname in args.values_of("INPUT").unwrap() {
-        let mut rdr = ReaderBuilder::new().has_headers(hdrs).from_path(infname)?;
-        let mut line = StringRecord::new();
-        while rdr.read_record(&mut line)? {
-            let name = line.get(namecol).expect(
-                &format!("Missing name on line {} of file {}",
-                        line.position().map(csv::Position::line).unwrap(), infname));
-            let count = str::parse(line.get(countcol)
-                    .expect(&format!("Missing count on line {} of file {}",
-                        line.position().map(csv::Position::line).unwrap(), infname))
-                ).expect(&format!("Invalid count on line {} of file {}",
-                    line.position().map(csv::Position::line).unwrap(), infname));
-            names.add(name, count);
+    // Bound the number of OS threads to a fixed-size worker pool (sized to
+    // available parallelism) rather than spawning one thread per input
+    // file, so that running against many input files doesn't exhaust
+    // system resources. Each worker tallies its chunk of files sequentially
+    // and merges its own partial `Combiner` before the results are
+    // combined at the end.
+    let num_workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(infnames.len());
+    let chunk_size = (infnames.len() + num_workers - 1) / num_workers;
+
+    let names = thread::scope(|scope| {
+        let handles: Vec<_> = infnames.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || {
+                let mut combined = Combiner::new();
+                for &infname in chunk {
+                    combined.merge(combine_file(infname, namecol, countcol, hdrs)?);
+                }
+                Ok::<_, std::io::Error>(combined)
+            }))
+            .collect();
+        let mut combined = Combiner::new();
+        for handle in handles {
+            combined.merge(handle.join().expect("worker thread panicked")?);
         }
-    }
+        Ok::<_, std::io::Error>(combined)
+    })?;
 
     match out {
-        Some(filew) => names.write_to(filew),
-        None => names.write_to(csv::Writer::from_writer(stdout())),
+        Some(filew) => names.write_to(filew, limit, ascending, stats),
+        None => names.write_to(csv::Writer::from_writer(stdout()), limit, ascending, stats),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Combiner;
+
+    fn written_rows(combiner: Combiner, limit: usize, ascending: bool) -> Vec<(String, u64)> {
+        let mut buf = Vec::new();
+        combiner.write_to(csv::Writer::from_writer(&mut buf), limit, ascending, false).unwrap();
+        csv::ReaderBuilder::new().has_headers(false).from_reader(buf.as_slice())
+            .into_records()
+            .map(|rec| {
+                let rec = rec.unwrap();
+                (rec.get(0).unwrap().to_string(), rec.get(1).unwrap().parse().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_sums_counts_on_collision() {
+        let mut a = Combiner::new();
+        a.add("alice", 2);
+        a.add("bob", 1);
+        let mut b = Combiner::new();
+        b.add("alice", 3);
+        b.add("carol", 5);
+        a.merge(b);
+
+        // alice and carol tie at 5, and write_to leaves the order of equal
+        // counts undefined, so compare the rows sorted by name.
+        let mut rows = written_rows(a, 0, false);
+        rows.sort_unstable();
+        assert_eq!(rows, vec![
+            ("alice".to_string(), 5),
+            ("bob".to_string(), 1),
+            ("carol".to_string(), 5),
+        ]);
+    }
+
+    #[test]
+    fn write_to_applies_limit_then_ascending() {
+        let mut combined = Combiner::new();
+        combined.add("alice", 2);
+        combined.add("bob", 1);
+        combined.add("carol", 5);
+        combined.add("dave", 3);
+
+        // Limit to the 2 most frequent, then flip to ascending order.
+        let rows = written_rows(combined, 2, true);
+        assert_eq!(rows, vec![
+            ("dave".to_string(), 3),
+            ("carol".to_string(), 5),
+        ]);
+    }
+
+    #[test]
+    fn stats_reports_cardinality_and_tied_mode() {
+        let mut combined = Combiner::new();
+        combined.add("alice", 5);
+        combined.add("bob", 5);
+        combined.add("carol", 1);
+
+        let (cardinality, mode) = combined.stats();
+        assert_eq!(cardinality, 3);
+        let (name, count) = mode.expect("non-empty combiner has a mode");
+        assert_eq!(count, 5);
+        assert!(name == "alice" || name == "bob");
+    }
+
+    #[test]
+    fn stats_on_empty_combiner_has_no_mode() {
+        let (cardinality, mode) = Combiner::new().stats();
+        assert_eq!(cardinality, 0);
+        assert!(mode.is_none());
     }
 }
@@ -4,15 +4,17 @@ use clap::{
 };
 use rand::{
     thread_rng,
+    Rng,
+    SeedableRng,
+    rngs::StdRng,
 };
 use jane_doe::{
-    UniqueSampler,
-    SampleFrom,
-    us_names,
+    LocaleRegistry,
+    locale_names,
 };
 
-fn process(sampler: impl SampleFrom<Item=String>, count: usize) {
-    for s in UniqueSampler::new(&sampler, count, &mut thread_rng()) {
+fn process(registry: &LocaleRegistry, locale: &str, count: usize, rng: &mut impl Rng) {
+    for s in locale_names(registry, locale, count, rng) {
         println!("{}", s);
     }
 }
@@ -30,27 +32,54 @@ fn main() {
              .short("l")
              .long("locale")
              .value_name("LOCALE")
-             .help("Which locale to get names from (default US)")
+             .help("Which locale to get names from (default us)")
              .takes_value(true))
         .arg(Arg::with_name("show")
              .short("s")
              .long("show-locales")
              .help("Display a listing of supported locales"))
+        .arg(Arg::with_name("seed")
+             .long("seed")
+             .value_name("SEED")
+             .help("Seed the RNG for reproducible output (default random)")
+             .takes_value(true))
+        .arg(Arg::with_name("given_csv")
+             .long("given-csv")
+             .value_name("FILE")
+             .help("Custom given-names frequency csv (name,count); registers --locale using it, must be paired with --surnames-csv")
+             .takes_value(true))
+        .arg(Arg::with_name("surnames_csv")
+             .long("surnames-csv")
+             .value_name("FILE")
+             .help("Custom surnames frequency csv (name,count); registers --locale using it, must be paired with --given-csv")
+             .takes_value(true))
         .get_matches();
 
-    // XXX only locale currently supported is "US"
+    let mut registry = LocaleRegistry::with_defaults();
+    let locale = args.value_of("locale").unwrap_or("us").to_string();
+
+    match (args.value_of("given_csv"), args.value_of("surnames_csv")) {
+        (Some(given), Some(surnames)) => registry.register_custom(locale.clone(), given, surnames),
+        (None, None) => {},
+        _ => panic!("--given-csv and --surnames-csv must be supplied together"),
+    }
+
     if args.is_present("show") {
-        println!("us");
+        for locale in registry.locales() {
+            println!("{}", locale);
+        }
         return;
     }
-    let locale = args.value_of("locale").unwrap_or("us");
-    if locale != "us" {
-        panic!("unsupported locale");
-    }
 
     let count = args.value_of("count")
         .map(|s| str::parse(s).expect("count must be a positive integer"))
         .unwrap_or(1usize);
 
-    process(us_names(), count);
+    let seed = args.value_of("seed")
+        .map(|s| str::parse::<u64>(s).expect("seed must be a non-negative integer"));
+
+    match seed {
+        Some(seed) => process(&registry, &locale, count, &mut StdRng::seed_from_u64(seed)),
+        None => process(&registry, &locale, count, &mut thread_rng()),
+    }
 }
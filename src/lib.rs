@@ -7,6 +7,7 @@ use csv::{
 };
 use num_traits::{
     Zero,
+    ToPrimitive,
 };
 use rand::{
     thread_rng,
@@ -17,16 +18,17 @@ use rand::{
         },
     },
 };
-use bloomfilter::{
-    Bloom,
-};
 use std::{
     ops::{
         AddAssign,
     },
-    hash::{
-        Hash,
+    collections::{
+        BinaryHeap,
+        HashMap,
     },
+    cmp::Ordering,
+    path::PathBuf,
+    io::Read,
 };
 
 const ASSETS_DIR: include_dir::Dir = include_dir!("src/assets");
@@ -45,55 +47,35 @@ pub trait SampleFrom {
     }
 }
 
-/// A stream of unique random samples from an underlying sampler.
-///
-/// The uniqueness of returned elements is guaranteed, but the iterator
-/// may hang if there are insufficiently many unique values in the underlying
-/// collection.
-///
-/// Ensuring that the number of unique values is at least 2 times `count` should
-/// be sufficient.
-pub struct UniqueSampler<'a, S: SampleFrom, R: Rng> {
-    source: &'a S,
-    seen: Bloom<S::Item>,
-    remaining: usize,
-    rng: &'a mut R,
+/// One candidate in the bounded min-heap used by
+/// [`FreqChoice::sample_without_replacement`], ordered so that the
+/// *smallest* key sorts as the *greatest* element. This lets a plain
+/// `BinaryHeap` (a max-heap) be used to evict the smallest key once the
+/// heap grows past the requested size.
+struct HeapEntry<T> {
+    key: f64,
+    value: T,
 }
 
-impl<'a, S: SampleFrom, R: Rng> UniqueSampler<'a,S,R>
-where S::Item: Hash,
-{
-    /// Create a stream of unique random samples from the underlying collection.
-    pub fn new(source: &'a S, count: usize, rng: &'a mut R) -> Self {
-        Self {
-            source,
-            seen: Bloom::new_for_fp_rate(count, 0.1),
-            remaining: count,
-            rng,
-        }
+impl<T> PartialEq for HeapEntry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
     }
 }
 
-impl<'a, S: SampleFrom, R: Rng> Iterator for UniqueSampler<'a,S,R>
-where S::Item: Hash,
-{
-    type Item = S::Item;
+impl<T> Eq for HeapEntry<T> {}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.remaining == 0 {
-            return None;
-        }
-        loop {
-            let x = self.source.sample_using(self.rng);
-            if ! self.seen.check_and_set(&x) {
-                self.remaining -= 1;
-                return Some(x);
-            }
-        }
+impl<T> PartialOrd for HeapEntry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        other.key.partial_cmp(&self.key)
     }
 }
 
-
+impl<T> Ord for HeapEntry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).expect("NaN key while weighted sampling")
+    }
+}
 
 /// Store values along with their frequencies.
 ///
@@ -182,6 +164,45 @@ where N: Copy + Zero + PartialOrd + SampleUniform,
     }
 }
 
+impl<N,T> FreqChoice<N,T>
+where N: Copy + Zero + ToPrimitive,
+      T: Clone,
+{
+    /// Draws `k` items without replacement, weighted by their stored
+    /// frequencies, using the Efraimidis-Spirakis A-Res algorithm.
+    ///
+    /// Each item's individual frequency `w_i` is recovered from the
+    /// cumulative totals, a key `u_i.powf(1.0 / w_i)` is computed from a
+    /// fresh uniform draw `u_i`, and the `k` items with the largest keys
+    /// are kept using a bounded min-heap of size `k` in a single
+    /// `O(n log k)` pass. This is exact (no rejection and no risk of
+    /// hanging), unlike rejection sampling against a probabilistic
+    /// filter. The result is returned in decreasing order of key.
+    ///
+    /// If `k` is at least the number of items with positive frequency,
+    /// all of them are returned. Items with zero frequency (which
+    /// [`from_items`](Self::from_items) permits) are never selected.
+    pub fn sample_without_replacement(&self, k: usize, rng: &mut impl Rng) -> Vec<T> {
+        let mut heap: BinaryHeap<HeapEntry<T>> = BinaryHeap::with_capacity(k + 1);
+        let mut prev = N::zero();
+        for (cumulative, value) in &self.data {
+            let w = cumulative.to_f64().expect("frequency out of range for f64")
+                - prev.to_f64().expect("frequency out of range for f64");
+            prev = *cumulative;
+            if w <= 0.0 {
+                continue;
+            }
+            let u: f64 = rng.gen();
+            let key = u.powf(1.0 / w);
+            heap.push(HeapEntry { key, value: value.clone() });
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+        heap.into_sorted_vec().into_iter().map(|entry| entry.value).collect()
+    }
+}
+
 impl<N,T> SampleFrom for FreqChoice<N,T>
 where N: Copy + Zero + PartialOrd + SampleUniform,
       T: Clone,
@@ -194,72 +215,297 @@ where N: Copy + Zero + PartialOrd + SampleUniform,
     }
 }
 
-struct SamplerPair<A,B,F> {
-    first: A,
-    second: B,
-    combiner: F,
+/// Where to load a locale's name-frequency table from: a CSV file bundled
+/// into the binary, or a CSV path supplied by the caller at runtime.
+enum CsvSource {
+    Asset(&'static str),
+    Path(PathBuf),
 }
 
-impl<A,B,F,T> SampleFrom for SamplerPair<A,B,F>
-where A: SampleFrom,
-      B: SampleFrom,
-      F: Fn(<A as SampleFrom>::Item, <B as SampleFrom>::Item) -> T
-{
-    type Item = T;
-
-    fn sample_using(&self, rng: &mut impl Rng) -> Self::Item {
-        (self.combiner)(self.first.sample_using(rng), self.second.sample_using(rng))
+impl CsvSource {
+    fn load(&self) -> FreqChoice<u64, String> {
+        match self {
+            CsvSource::Asset(fname) => build_freq_choice(
+                ReaderBuilder::new().has_headers(false).from_reader(get_asset_file(fname))
+            ),
+            CsvSource::Path(path) => build_freq_choice(
+                ReaderBuilder::new().has_headers(false).from_path(path)
+                    .unwrap_or_else(|e| panic!("cannot open csv file '{}': {}", path.display(), e))
+            ),
+        }
     }
 }
 
-#[derive(Debug)]
-pub enum CsvSource {
-    USGiven,
-    USSurnames,
-}
-
-fn get_asset_file(src: CsvSource) -> &'static [u8] {
-    let fname = match src {
-        CsvSource::USGiven => "us-given.csv",
-        CsvSource::USSurnames => "us-surnames.csv",
-    };
+fn get_asset_file(fname: &str) -> &'static [u8] {
     ASSETS_DIR.get_file(fname)
-        .expect(&format!("missing asset file '{}'", fname))
+        .unwrap_or_else(|| panic!("missing asset file '{}'", fname))
         .contents()
 }
 
-pub fn get_source_sampler(src: CsvSource) -> impl SampleFrom<Item=String> {
+fn build_freq_choice<R: Read>(rdr: csv::Reader<R>) -> FreqChoice<u64, String> {
     FreqChoice::from_items(
-        ReaderBuilder::new()
-            .has_headers(false)
-            .from_reader(get_asset_file(src))
-            .into_records()
+        rdr.into_records()
             .map(|recres| {
-                let line = recres.expect("mis-formatted csv asset file");
-                (str::parse::<u64>(line.get(1).expect("missing count in csv asset file"))
-                 .expect("invalid count in csv asset file"),
-                 line.get(0).expect("missing name in csv asset file").to_string())
+                let line = recres.expect("mis-formatted csv file");
+                (str::parse::<u64>(line.get(1).expect("missing count in csv file"))
+                 .expect("invalid count in csv file"),
+                 line.get(0).expect("missing name in csv file").to_string())
             })
-    ).expect("non-positive frequency in csv asset file")
+    ).expect("non-positive frequency in csv file")
 }
 
-/// Get a sampler for American names (given + surname).
-pub fn us_names() -> impl SampleFrom<Item=String> {
-    SamplerPair {
-        first: get_source_sampler(CsvSource::USGiven),
-        second: get_source_sampler(CsvSource::USSurnames),
-        combiner: |mut first: String, last: String| {
-            first += " ";
-            first += &last;
-            first
-        },
+/// How to build a full name out of a locale's sampled given name and
+/// surname, e.g. `"First Last"` or `"Last First"`.
+pub type NameCombiner = fn(String, String) -> String;
+
+fn western_order(mut first: String, last: String) -> String {
+    first += " ";
+    first += &last;
+    first
+}
+
+struct LocaleEntry {
+    given: CsvSource,
+    surnames: CsvSource,
+    combine: NameCombiner,
+}
+
+/// A data-driven registry mapping locale codes to the name-frequency
+/// tables (and combining rule) used to sample full names for that locale.
+///
+/// [`with_defaults`](Self::with_defaults) populates the locales bundled
+/// into the binary, and [`register_custom`](Self::register_custom) adds
+/// locales backed by arbitrary user-supplied CSV files in the same
+/// `name,count` format, so new locales or one-off custom frequency lists
+/// can be used without recompiling.
+pub struct LocaleRegistry {
+    entries: HashMap<String, LocaleEntry>,
+}
+
+impl LocaleRegistry {
+    /// Builds a registry containing just the locales bundled with this
+    /// crate.
+    pub fn with_defaults() -> Self {
+        let mut entries = HashMap::new();
+        entries.insert("us".to_string(), LocaleEntry {
+            given: CsvSource::Asset("us-given.csv"),
+            surnames: CsvSource::Asset("us-surnames.csv"),
+            combine: western_order,
+        });
+        Self { entries }
+    }
+
+    /// Registers a custom locale backed by user-supplied given-name and
+    /// surname CSV files, each a list of `name,count` rows, combined in
+    /// the default `"First Last"` order.
+    ///
+    /// Use [`register_custom_with_combiner`](Self::register_custom_with_combiner)
+    /// to supply a different combining rule, e.g. surname-first.
+    pub fn register_custom(
+        &mut self,
+        locale: impl Into<String>,
+        given_csv: impl Into<PathBuf>,
+        surnames_csv: impl Into<PathBuf>,
+    ) {
+        self.register_custom_with_combiner(locale, given_csv, surnames_csv, western_order);
+    }
+
+    /// Registers a custom locale like [`register_custom`](Self::register_custom),
+    /// but with an explicit `combine` rule instead of the default
+    /// `"First Last"` order.
+    pub fn register_custom_with_combiner(
+        &mut self,
+        locale: impl Into<String>,
+        given_csv: impl Into<PathBuf>,
+        surnames_csv: impl Into<PathBuf>,
+        combine: NameCombiner,
+    ) {
+        self.entries.insert(locale.into(), LocaleEntry {
+            given: CsvSource::Path(given_csv.into()),
+            surnames: CsvSource::Path(surnames_csv.into()),
+            combine,
+        });
     }
+
+    /// Lists the currently-registered locale codes, in sorted order.
+    pub fn locales(&self) -> Vec<&str> {
+        let mut codes: Vec<&str> = self.entries.keys().map(String::as_str).collect();
+        codes.sort_unstable();
+        codes
+    }
+
+    fn get(&self, locale: &str) -> &LocaleEntry {
+        self.entries.get(locale)
+            .unwrap_or_else(|| panic!("unsupported locale '{}'", locale))
+    }
+}
+
+/// Get `count` unique names for `locale` (given name + surname), each
+/// sampled exactly (without replacement) and weighted by that locale's
+/// name frequencies.
+///
+/// If `locale`'s given-name or surname table has fewer than `count`
+/// items with positive frequency, the returned `Vec` is shorter than
+/// `count` (see [`FreqChoice::sample_without_replacement`]) and a
+/// warning naming the shortfall is printed to stderr.
+pub fn locale_names(registry: &LocaleRegistry, locale: &str, count: usize, rng: &mut impl Rng) -> Vec<String> {
+    let entry = registry.get(locale);
+    let given = entry.given.load();
+    let surnames = entry.surnames.load();
+    let names: Vec<String> = given.sample_without_replacement(count, rng)
+        .into_iter()
+        .zip(surnames.sample_without_replacement(count, rng))
+        .map(|(first, last)| (entry.combine)(first, last))
+        .collect();
+    if names.len() < count {
+        eprintln!(
+            "warning: locale '{}' only has {} unique name(s) available, fewer than the {} requested",
+            locale, names.len(), count
+        );
+    }
+    names
+}
+
+/// Get `count` unique American names (given name + surname), each sampled
+/// exactly (without replacement) and weighted by population frequency.
+///
+/// See [`locale_names`] for the behavior when `count` exceeds the number
+/// of available names.
+pub fn us_names(count: usize, rng: &mut impl Rng) -> Vec<String> {
+    locale_names(&LocaleRegistry::with_defaults(), "us", count, rng)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{us_names, locale_names, FreqChoice, LocaleRegistry};
+    use rand::{
+        SeedableRng,
+        rngs::StdRng,
+    };
+    use std::{
+        collections::HashSet,
+        fs,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn us_names_reproducible_with_seed() {
+        let names1 = us_names(5, &mut StdRng::seed_from_u64(42));
+        let names2 = us_names(5, &mut StdRng::seed_from_u64(42));
+        assert_eq!(names1, names2);
+        assert_eq!(names1.len(), 5);
+    }
+
+    /// Writes `contents` to a fresh, uniquely-named temp file and returns
+    /// its path.
+    fn write_temp_csv(contents: &str) -> std::path::PathBuf {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir()
+            .join(format!("jane-doe-test-{}-{}.csv", std::process::id(), n));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn custom_locale_registers_and_samples() {
+        let given_path = write_temp_csv("Ana,3\nBea,1\n");
+        let surnames_path = write_temp_csv("Smith,2\nJones,1\n");
+
+        let mut registry = LocaleRegistry::with_defaults();
+        registry.register_custom("xx", given_path.clone(), surnames_path.clone());
+
+        let locales = registry.locales();
+        assert!(locales.contains(&"us"));
+        assert!(locales.contains(&"xx"));
+
+        let names = locale_names(&registry, "xx", 2, &mut StdRng::seed_from_u64(1));
+        assert_eq!(names.len(), 2);
+        for name in &names {
+            let (first, last) = name.split_once(' ').expect("western order: 'First Last'");
+            assert!(["Ana", "Bea"].contains(&first));
+            assert!(["Smith", "Jones"].contains(&last));
+        }
+
+        fs::remove_file(&given_path).unwrap();
+        fs::remove_file(&surnames_path).unwrap();
+    }
+
+    #[test]
+    fn custom_locale_honors_explicit_combiner() {
+        let given_path = write_temp_csv("Ana,1\n");
+        let surnames_path = write_temp_csv("Smith,1\n");
+
+        let mut registry = LocaleRegistry::with_defaults();
+        registry.register_custom_with_combiner(
+            "yy", given_path.clone(), surnames_path.clone(),
+            |first, last| format!("{}, {}", last, first),
+        );
+
+        let names = locale_names(&registry, "yy", 1, &mut StdRng::seed_from_u64(1));
+        assert_eq!(names, vec!["Smith, Ana".to_string()]);
+
+        fs::remove_file(&given_path).unwrap();
+        fs::remove_file(&surnames_path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported locale")]
+    fn get_panics_for_unregistered_locale() {
+        let registry = LocaleRegistry::with_defaults();
+        registry.get("nonexistent");
+    }
+
+    #[test]
+    fn locale_names_returns_fewer_than_count_on_shortfall() {
+        let given_path = write_temp_csv("Ana,1\nBea,1\n");
+        let surnames_path = write_temp_csv("Smith,1\n");
+
+        let mut registry = LocaleRegistry::with_defaults();
+        registry.register_custom("zz", given_path.clone(), surnames_path.clone());
+
+        let names = locale_names(&registry, "zz", 5, &mut StdRng::seed_from_u64(1));
+        assert!(names.len() < 5);
+
+        fs::remove_file(&given_path).unwrap();
+        fs::remove_file(&surnames_path).unwrap();
+    }
+
+    #[test]
+    fn sample_without_replacement_has_no_duplicates() {
+        let choice = FreqChoice::from_items(vec![
+            (3u64, "a"), (1, "b"), (4, "c"), (1, "d"), (5, "e"),
+        ]).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let sampled = choice.sample_without_replacement(3, &mut rng);
+        assert_eq!(sampled.len(), 3);
+        let unique: HashSet<_> = sampled.iter().collect();
+        assert_eq!(unique.len(), sampled.len());
+    }
+
+    #[test]
+    fn sample_without_replacement_passes_through_when_k_at_least_n() {
+        let choice = FreqChoice::from_items(vec![
+            (3u64, "a"), (1, "b"), (4, "c"),
+        ]).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut sampled = choice.sample_without_replacement(10, &mut rng);
+        sampled.sort_unstable();
+        assert_eq!(sampled, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn sample_without_replacement_skips_zero_weight_items() {
+        let choice = FreqChoice::from_items(vec![
+            (0u64, "never"), (1, "a"), (0, "also-never"),
+        ]).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let sampled = choice.sample_without_replacement(10, &mut rng);
+        assert_eq!(sampled, vec!["a"]);
+    }
 }